@@ -1,11 +1,14 @@
 use std::fs;
 use std::io;
 use std::net;
+use std::net::ToSocketAddrs;
 use std::path;
 use std::time::Duration;
 
 use rusb;
 use rusb::UsbContext;
+use serialport;
+use serialport::SerialPort;
 
 #[derive(Debug)]
 pub struct Usb {
@@ -17,12 +20,18 @@ pub struct Usb {
     _timeout: Duration,
     handle: rusb::DeviceHandle<rusb::Context>,
 }
-pub struct Serial {}
+pub struct Serial {
+    _port: String,
+    _baud_rate: u32,
+    handle: Box<dyn SerialPort>,
+}
 
 #[derive(Debug)]
 pub struct Network {
     _host: String,
     _port: u16,
+    _connect_timeout: Option<Duration>,
+    _write_timeout: Option<Duration>,
     stream: net::TcpStream,
 }
 
@@ -60,6 +69,143 @@ impl Usb {
             handle,
         })
     }
+
+    /// Open the device matching `vendor_id`/`product_id` and auto-detect its
+    /// bulk interface and endpoints instead of requiring them up front.
+    ///
+    /// The active configuration is walked interface by interface; the first
+    /// interface exposing a `Bulk` OUT/IN endpoint pair is selected, preferring
+    /// an interface of the USB printer class (`bInterfaceClass == 7`) when one
+    /// is present. Returns [`rusb::Error::NotFound`] if no interface qualifies.
+    pub fn discover(
+        vendor_id: u16,
+        product_id: u16,
+        timeout: Duration,
+    ) -> Result<Usb, rusb::Error> {
+        let context = rusb::Context::new()?;
+
+        let device = context
+            .devices()?
+            .iter()
+            .find(|device| {
+                let desc = device.device_descriptor().unwrap();
+                desc.vendor_id() == vendor_id && desc.product_id() == product_id
+            })
+            .ok_or(rusb::Error::NotFound)?;
+
+        let config = device.active_config_descriptor()?;
+
+        let mut fallback: Option<(u8, u8, u8)> = None;
+        let mut selected: Option<(u8, u8, u8)> = None;
+        for interface in config.interfaces() {
+            for descriptor in interface.descriptors() {
+                let mut endpoint_in = None;
+                let mut endpoint_out = None;
+                for endpoint in descriptor.endpoint_descriptors() {
+                    if endpoint.transfer_type() != rusb::TransferType::Bulk {
+                        continue;
+                    }
+                    match endpoint.direction() {
+                        rusb::Direction::In => endpoint_in = Some(endpoint.address()),
+                        rusb::Direction::Out => endpoint_out = Some(endpoint.address()),
+                    }
+                }
+                if let (Some(in_addr), Some(out_addr)) = (endpoint_in, endpoint_out) {
+                    let found = (descriptor.interface_number(), in_addr, out_addr);
+                    if descriptor.class_code() == 7 {
+                        selected = Some(found);
+                        break;
+                    }
+                    fallback.get_or_insert(found);
+                }
+            }
+            if selected.is_some() {
+                break;
+            }
+        }
+
+        let (interface, endpoint_in_address, endpoint_out_address) =
+            selected.or(fallback).ok_or(rusb::Error::NotFound)?;
+
+        let mut handle = device.open()?;
+
+        handle.set_auto_detach_kernel_driver(true).unwrap_or_default();
+        handle.claim_interface(interface)?;
+        Ok(Usb {
+            _vendor_id: vendor_id,
+            _product_id: product_id,
+            _interface: interface,
+            _endpoint_in_address: endpoint_in_address,
+            _endpoint_out_address: endpoint_out_address,
+            _timeout: timeout,
+            handle,
+        })
+    }
+}
+
+/// Real-time printer status decoded from the `DLE EOT 1`, `DLE EOT 2` and
+/// `DLE EOT 4` transmit-status replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrinterStatus {
+    /// Paper is loaded and feed is possible.
+    pub paper_present: bool,
+    /// The cover (or roll-paper door) is open.
+    pub cover_open: bool,
+    /// The cash drawer kick-out connector pin 3 is high.
+    pub drawer_open: bool,
+    /// An unrecoverable or auto-recoverable error is asserted.
+    pub error: bool,
+}
+
+impl Usb {
+    /// Read up to `buf.len()` bytes from the IN endpoint, returning the number read.
+    pub fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self
+            .handle
+            .read_bulk(self._endpoint_in_address, buf, self._timeout)
+        {
+            Ok(n) => Ok(n),
+            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+        }
+    }
+
+    /// Send an ESC/POS real-time status request (`DLE EOT n`) and
+    /// return the one-byte response read back from the IN endpoint.
+    pub fn transact(&mut self, n: u8) -> io::Result<u8> {
+        use io::Write;
+        self.write_all(&[0x10, 0x04, n])?;
+        let mut buf = [0u8; 1];
+        let read = self.read(&mut buf)?;
+        if read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "no status byte returned by printer",
+            ));
+        }
+        Ok(buf[0])
+    }
+
+    /// Poll the printer for its real-time status, decoding the drawer bit from
+    /// `DLE EOT 1`, the cover and error bits from `DLE EOT 2` and the
+    /// paper-present bit from `DLE EOT 4`.
+    pub fn status(&mut self) -> io::Result<PrinterStatus> {
+        let printer = self.transact(1)?;
+        let offline = self.transact(2)?;
+        let paper = self.transact(4)?;
+        Ok(PrinterStatus {
+            // Both roll-paper-end sensors report "paper present" when clear.
+            paper_present: paper & 0b0110_0000 == 0,
+            cover_open: offline & 0b0000_0100 != 0,
+            drawer_open: printer & 0b0000_0100 != 0,
+            error: offline & 0b0100_0000 != 0,
+        })
+    }
+}
+
+impl io::Read for Usb {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Usb::read(self, buf)
+    }
 }
 
 impl io::Write for Usb {
@@ -75,20 +221,111 @@ impl io::Write for Usb {
     }
 }
 
+impl Serial {
+    pub fn new(
+        port: &str,
+        baud_rate: u32,
+        data_bits: serialport::DataBits,
+        parity: serialport::Parity,
+        stop_bits: serialport::StopBits,
+        flow_control: serialport::FlowControl,
+        timeout: Duration,
+    ) -> Result<Serial, serialport::Error> {
+        let handle = serialport::new(port, baud_rate)
+            .data_bits(data_bits)
+            .parity(parity)
+            .stop_bits(stop_bits)
+            .flow_control(flow_control)
+            .timeout(timeout)
+            .open()?;
+        Ok(Serial {
+            _port: port.to_string(),
+            _baud_rate: baud_rate,
+            handle,
+        })
+    }
+}
+
+impl io::Write for Serial {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.handle.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.handle.flush()
+    }
+}
+
 impl Network {
     pub fn new(host: &str, port: u16) -> io::Result<Network> {
         let stream = net::TcpStream::connect((host, port))?;
         Ok(Network {
             _host: host.to_string(),
             _port: port,
+            _connect_timeout: None,
+            _write_timeout: None,
             stream,
         })
     }
+
+    /// Connect with a bounded connect timeout and a per-write timeout so a
+    /// powered-off printer can't hang the caller indefinitely.
+    pub fn with_timeout(
+        host: &str,
+        port: u16,
+        connect_timeout: Duration,
+        write_timeout: Duration,
+    ) -> io::Result<Network> {
+        let address = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "could not resolve host"))?;
+        let stream = net::TcpStream::connect_timeout(&address, connect_timeout)?;
+        stream.set_write_timeout(Some(write_timeout))?;
+        Ok(Network {
+            _host: host.to_string(),
+            _port: port,
+            _connect_timeout: Some(connect_timeout),
+            _write_timeout: Some(write_timeout),
+            stream,
+        })
+    }
+
+    /// Re-dial the saved host/port, honouring the configured connect and write
+    /// timeouts so recovery can't hang the caller during an outage.
+    fn reconnect(&mut self) -> io::Result<()> {
+        let stream = match self._connect_timeout {
+            Some(connect_timeout) => {
+                let address = (self._host.as_str(), self._port)
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "could not resolve host")
+                    })?;
+                net::TcpStream::connect_timeout(&address, connect_timeout)?
+            }
+            None => net::TcpStream::connect((self._host.as_str(), self._port))?,
+        };
+        stream.set_write_timeout(self._write_timeout)?;
+        self.stream = stream;
+        Ok(())
+    }
 }
 
 impl io::Write for Network {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.stream.write(buf)
+        match self.stream.write(buf) {
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+                ) =>
+            {
+                self.reconnect()?;
+                self.stream.write(buf)
+            }
+            other => other,
+        }
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -136,3 +373,101 @@ impl<W: io::Write> io::Write for File<W> {
         self.fobj.flush()
     }
 }
+
+/// A transport chosen at runtime, wrapping any of the concrete devices so that
+/// `Printer::new` can be handed a printer selected purely from configuration.
+pub enum Device {
+    Usb(Usb),
+    Serial(Serial),
+    Network(Network),
+    File(File<fs::File>),
+}
+
+impl Device {
+    /// Build a [`Device`] from a URI such as `usb://04b8:0202`,
+    /// `serial:///dev/ttyUSB0?baud=19200`, `tcp://192.168.0.50:9100` or
+    /// `file:///dev/usb/lp0`.
+    pub fn from_uri(uri: &str) -> io::Result<Device> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+
+        let (scheme, rest) = uri
+            .split_once("://")
+            .ok_or_else(|| invalid("missing scheme in device uri"))?;
+
+        match scheme {
+            "usb" => {
+                let (vendor, product) = rest
+                    .split_once(':')
+                    .ok_or_else(|| invalid("usb uri must be usb://<vendor>:<product>"))?;
+                let vendor_id = u16::from_str_radix(vendor, 16)
+                    .map_err(|_| invalid("invalid usb vendor id"))?;
+                let product_id = u16::from_str_radix(product, 16)
+                    .map_err(|_| invalid("invalid usb product id"))?;
+                let usb = Usb::discover(vendor_id, product_id, Duration::from_secs(1))
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                Ok(Device::Usb(usb))
+            }
+            "serial" => {
+                let (path, query) = match rest.split_once('?') {
+                    Some((path, query)) => (path, Some(query)),
+                    None => (rest, None),
+                };
+                let mut baud_rate = 9600;
+                if let Some(query) = query {
+                    for pair in query.split('&') {
+                        if let Some(("baud", value)) = pair.split_once('=') {
+                            baud_rate =
+                                value.parse().map_err(|_| invalid("invalid serial baud"))?;
+                        }
+                    }
+                }
+                let serial = Serial::new(
+                    path,
+                    baud_rate,
+                    serialport::DataBits::Eight,
+                    serialport::Parity::None,
+                    serialport::StopBits::One,
+                    serialport::FlowControl::None,
+                    Duration::from_secs(1),
+                )
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                Ok(Device::Serial(serial))
+            }
+            "tcp" => {
+                let (host, port) = rest
+                    .rsplit_once(':')
+                    .ok_or_else(|| invalid("tcp uri must be tcp://<host>:<port>"))?;
+                let port = port.parse().map_err(|_| invalid("invalid tcp port"))?;
+                let network = Network::with_timeout(
+                    host,
+                    port,
+                    Duration::from_secs(5),
+                    Duration::from_secs(5),
+                )?;
+                Ok(Device::Network(network))
+            }
+            "file" => Ok(Device::File(File::<fs::File>::from_path(rest)?)),
+            other => Err(invalid(&format!("unsupported device scheme `{}`", other))),
+        }
+    }
+}
+
+impl io::Write for Device {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Device::Usb(device) => device.write(buf),
+            Device::Serial(device) => device.write(buf),
+            Device::Network(device) => device.write(buf),
+            Device::File(device) => device.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Device::Usb(device) => device.flush(),
+            Device::Serial(device) => device.flush(),
+            Device::Network(device) => device.flush(),
+            Device::File(device) => device.flush(),
+        }
+    }
+}